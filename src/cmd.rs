@@ -1,132 +1,174 @@
-use std::sync::{Arc, Mutex};
-use crate::store::Store;
+use bytes::{Buf, BytesMut};
+use memchr::memchr;
 
-const CARRIAGE_RETURN: char = '\r';
-const ARRAY_DENOTE: char = '*';
-const BULK_STRING_DENOTE: char = '$';
+const ARRAY_DENOTE: u8 = b'*';
+const BULK_STRING_DENOTE: u8 = b'$';
+const BULK_STRING_PREFIX: char = '$';
 const SIMPLE_STRING_DENOTE: char = '+';
+const ARRAY_PREFIX: char = '*';
+const INTEGER_DENOTE: char = ':';
 const ERROR_DENOTE: char = '-';
-const NULL_DENOTE: &str = "-1";
 const CRLF: &str = "\r\n";
 
-const ERROR_UNKNOWN_COMMAND: &str = "ERR unknown command";
+/// Upper bound on a `*<count>` multibulk header, mirroring Redis's own
+/// hardcoded limit. Without this, an attacker-controlled count would drive
+/// an eager `Vec::with_capacity` allocation request before a single element
+/// has actually arrived — a client sending `*999999999999\r\n` would trigger
+/// a multi-terabyte allocation that aborts the whole process, not just that
+/// connection.
+const MAX_MULTIBULK_LEN: i64 = 1024 * 1024;
 
+/// A value is UTF-8 text, not raw bytes — see `parse_bulk_string` for why.
 pub enum RespValue {
     SimpleString(String),
     BulkString(String),
+    Integer(i64),
+    Array(Vec<RespValue>),
+    Null,
     Error(String),
 }
 
 impl RespValue {
     pub fn encode(self) -> String {
-        match &self {
+        match self {
             RespValue::SimpleString(val) => format!("{SIMPLE_STRING_DENOTE}{val}{CRLF}"),
-            RespValue::BulkString(val) => format!("{BULK_STRING_DENOTE}{val}{CRLF}"),
+            RespValue::BulkString(val) => format!("{BULK_STRING_PREFIX}{}{CRLF}{val}{CRLF}", val.len()),
+            RespValue::Integer(val) => format!("{INTEGER_DENOTE}{val}{CRLF}"),
+            RespValue::Array(items) => {
+                let mut encoded = format!("{ARRAY_PREFIX}{}{CRLF}", items.len());
+                for item in items {
+                    encoded.push_str(&item.encode());
+                }
+                encoded
+            }
+            RespValue::Null => format!("{BULK_STRING_PREFIX}-1{CRLF}"),
             RespValue::Error(msg) => format!("{ERROR_DENOTE}{msg}{CRLF}")
         }
     }
 }
 
 
-/// Deserialize array command
-///
-/// # Arguments
+/// Why `parse_command` couldn't hand back a `Command`.
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    /// `buf` doesn't yet hold a complete frame. The caller should leave
+    /// `buf` untouched, read more bytes from the socket, append them, and
+    /// retry — exactly the `NATS`-style incremental parse loop.
+    Incomplete,
+    /// `buf` held a complete frame, but it violates the RESP protocol (a
+    /// malformed length, a null command name, an empty array, ...). The
+    /// message is a ready-to-encode RESP error description; the caller
+    /// should reply with it and close the connection, since the stream is
+    /// no longer in a state we can safely resynchronize from.
+    Protocol(String),
+}
+
+/// Find the next `\r\n` in `buf` starting at `from`, returning the index of
+/// the `\r`.
+fn find_crlf(buf: &[u8], from: usize) -> Option<usize> {
+    let mut start = from;
+    while let Some(i) = memchr(b'\r', &buf[start..]) {
+        let cr = start + i;
+        if buf.get(cr + 1) == Some(&b'\n') {
+            return Some(cr);
+        }
+        start = cr + 1;
+    }
+    None
+}
+
+/// Parse one `$<len>\r\n<data>\r\n` bulk string starting at `pos`.
 ///
-/// * `cmd` - Command string
+/// `Command`/`RespValue` are `String`-based, so payloads are UTF-8 text
+/// (binary-safe only in the sense that embedded `CRLF` doesn't confuse the
+/// framing, since it's found by length rather than by scanning for `\r\n`).
+/// A payload that isn't valid UTF-8 is rejected as a protocol error rather
+/// than silently replacing the offending bytes with U+FFFD, which would
+/// corrupt the value and change its length.
 ///
-/// # Returns
+/// Returns the parsed element and the index just past its trailing CRLF.
+fn parse_bulk_string(buf: &[u8], pos: usize) -> Result<(Option<String>, usize), ParseError> {
+    match buf.get(pos) {
+        Some(&BULK_STRING_DENOTE) => {}
+        Some(other) => return Err(ParseError::Protocol(format!("ERR Protocol error: expected '$', got '{}'", *other as char))),
+        None => return Err(ParseError::Incomplete),
+    }
+    let len_end = find_crlf(buf, pos + 1).ok_or(ParseError::Incomplete)?;
+    let len: i64 = std::str::from_utf8(&buf[pos + 1..len_end])
+        .map_err(|_| ParseError::Protocol("ERR Protocol error: invalid bulk length".to_string()))?
+        .parse()
+        .map_err(|_| ParseError::Protocol("ERR Protocol error: invalid bulk length".to_string()))?;
+
+    if len == -1 {
+        return Ok((None, len_end + 2));
+    }
+    if len < -1 {
+        return Err(ParseError::Protocol("ERR Protocol error: invalid bulk length".to_string()));
+    }
+
+    let content_start = len_end + 2;
+    let content_end = content_start + len as usize;
+    // Require the payload plus its trailing CRLF before consuming anything.
+    if buf.len() < content_end + 2 {
+        return Err(ParseError::Incomplete);
+    }
+
+    let content = std::str::from_utf8(&buf[content_start..content_end])
+        .map_err(|_| ParseError::Protocol("ERR Protocol error: bulk string is not valid UTF-8".to_string()))?
+        .to_string();
+    Ok((Some(content), content_end + 2))
+}
+
+/// Scan `buf` for a single complete `*<count>\r\n$<len>\r\n...` frame.
 ///
-/// List of commands parsed from ReSP format
-pub fn deserialize_command_into_array(cmd: &String) -> Option<Vec<Option<String>>> {
-    let cmd_len = cmd.len();
-    if cmd_len == 0 {
-        return None;
-    }
-
-    let mut cmd_array: Vec<Option<String>> = vec![];
-
-    // Flow-control pointer vars
-    let mut cur_idx = 0;
-    let mut cmd_iterator = cmd.chars();
-    let mut parsing_array_len = false;
-    let mut parsing_array_content = false;
-    let mut parsing_bulk_string_len = false;
-    let mut array_lower_bound = 0;
-    let mut array_prefix_len = -1;
-    let mut bulk_string_prefix_len = -1;
-    let mut bulk_string_len_lower_bound = 0;
-
-    while cur_idx < cmd_len {
-        let cur_char = cmd_iterator.next().unwrap();
-        match cur_char {
-            ARRAY_DENOTE => {
-                parsing_array_len = true;
-                array_lower_bound = cur_idx + 1; // Next char
-            }
-            CARRIAGE_RETURN => {
-                if array_prefix_len == 0 {
-                    break;
-                }
-                if parsing_array_len {
-                    let prefix_length_str = &cmd[array_lower_bound..cur_idx];
-                    match prefix_length_str.parse::<i64>() {
-                        Ok(val) => {
-                            array_prefix_len = val;
-                            if array_prefix_len == -1 {
-                                return None;
-                            }
-                        }
-                        Err(e) => {
-                            println!("error parsing integer: {}", e)
-                        }
-                    }
-                    parsing_array_len = false;
-                } else if parsing_bulk_string_len {
-                    let bulk_string_prefix_len_str = &cmd[bulk_string_len_lower_bound..cur_idx];
-                    match bulk_string_prefix_len_str.parse::<i64>() {
-                        Ok(val) => {
-                            bulk_string_prefix_len = val;
-                        }
-                        Err(e) => {
-                            println!("error parsing integer: {}", e)
-                        }
-                    }
-                    // If got the length
-                    parsing_bulk_string_len = false;
-                    parsing_array_content = true;
-                }
+/// Returns the parsed elements and the number of bytes the frame occupied,
+/// without consuming anything from `buf` itself — the caller advances it.
+fn parse_array(buf: &[u8]) -> Result<(Vec<Option<String>>, usize), ParseError> {
+    if buf.first() != Some(&ARRAY_DENOTE) {
+        return Err(ParseError::Protocol(format!("ERR Protocol error: expected '*', got '{}'", buf[0] as char)));
+    }
+    let count_end = find_crlf(buf, 1).ok_or(ParseError::Incomplete)?;
+    let count: i64 = std::str::from_utf8(&buf[1..count_end])
+        .map_err(|_| ParseError::Protocol("ERR Protocol error: invalid multibulk length".to_string()))?
+        .parse()
+        .map_err(|_| ParseError::Protocol("ERR Protocol error: invalid multibulk length".to_string()))?;
 
-                // Skip next LF
-                cmd_iterator.next();
-                cur_idx += 1;
-            }
-            BULK_STRING_DENOTE => {
-                bulk_string_len_lower_bound = cur_idx + 1;
-                parsing_bulk_string_len = true;
-            }
-            _ => {
-                if parsing_array_content {
-                    if bulk_string_prefix_len == -1 {
-                        cmd_array.push(None);
-                    } else {
-                        let content_slice =
-                            &cmd[cur_idx..cur_idx + bulk_string_prefix_len as usize];
-
-                        cmd_array.push(Some(content_slice.to_string()));
-
-                        // Skip processed bulk string prefix size
-                        cur_idx += bulk_string_prefix_len as usize;
-                        for _ in 0..=bulk_string_prefix_len - 1 {
-                            cmd_iterator.next();
-                        }
-                        parsing_array_content = false;
-                    }
-                }
-            }
-        };
-        cur_idx += 1;
+    if !(0..=MAX_MULTIBULK_LEN).contains(&count) {
+        return Err(ParseError::Protocol("ERR Protocol error: invalid multibulk length".to_string()));
+    }
+
+    let mut cmd_array = Vec::with_capacity(count as usize);
+    let mut pos = count_end + 2;
+    for _ in 0..count {
+        let (element, next_pos) = parse_bulk_string(buf, pos)?;
+        cmd_array.push(element);
+        pos = next_pos;
+    }
+    Ok((cmd_array, pos))
+}
+
+/// Parse the next complete `Command` off the front of `buf`, like a
+/// `memchr`-based incremental scanner rather than a one-shot `String` parse.
+///
+/// On success, `buf` is advanced past exactly the bytes the frame consumed,
+/// so any pipelined commands behind it stay buffered for the next call. If
+/// `buf` is empty, returns `Ok(None)`. If a frame has started but isn't
+/// fully buffered yet, returns `Err(ParseError::Incomplete)` and leaves
+/// `buf` untouched so the connection loop can read more and retry. If a
+/// complete frame arrived but violates the protocol, returns
+/// `Err(ParseError::Protocol(_))`.
+///
+/// # Arguments
+///
+/// * `buf` - Byte buffer owned by the connection loop across reads
+pub fn parse_command(buf: &mut BytesMut) -> Result<Option<Command>, ParseError> {
+    if buf.is_empty() {
+        return Ok(None);
     }
-    Some(cmd_array)
+
+    let (cmd_array, consumed) = parse_array(buf)?;
+    buf.advance(consumed);
+    parse_cmd(cmd_array).map(Some).map_err(ParseError::Protocol)
 }
 
 
@@ -135,113 +177,122 @@ pub struct Command {
     pub args: Vec<String>,
 }
 
-/// Parse simple command with 1 argument only
+/// Turn a parsed bulk-string array into a `Command`.
 ///
 /// # Arguments
 ///
-/// * `cmd_array` - Command array (including argument)
-pub fn parse_cmd(cmd_array: Vec<Option<String>>) -> Command {
-    let cmd_str = match cmd_array.get(0) {
-        Some(main_cmd) => main_cmd.as_ref().unwrap().to_string(),
-        None => {
-            panic!("Command is null");
-        }
-    };
+/// * `cmd_array` - Command array (including arguments)
+pub fn parse_cmd(cmd_array: Vec<Option<String>>) -> Result<Command, String> {
+    let mut elements = cmd_array.into_iter();
 
-    let mut cmd_args = vec![];
+    let cmd_str = elements.next()
+        .ok_or_else(|| "ERR Protocol error: empty command".to_string())?
+        .ok_or_else(|| "ERR Protocol error: command name can't be null".to_string())?;
 
-    // Add arguments
-    for (_, arg) in cmd_array.iter().skip(1).enumerate() {
-        cmd_args.push(arg.as_ref().unwrap().to_string());
+    let mut cmd_args = Vec::new();
+    for arg in elements {
+        cmd_args.push(arg.ok_or_else(|| "ERR Protocol error: unexpected null bulk string in arguments".to_string())?);
     }
 
-    Command {
+    Ok(Command {
         cmd: cmd_str,
         args: cmd_args,
-    }
+    })
 }
 
 
+// --- TESTING ---
+
+#[cfg(test)]
+mod cmd_tests {
+    use bytes::BytesMut;
+    use super::{parse_command, ParseError, RespValue};
 
-/// Handle command RESP-format response
-///
-/// # Arguments
-///
-/// * `cmd`: Command
-pub fn handle_command_response(command: Command, client_store: &Arc<Mutex<Store>>) -> String {
-    let cmd = command.cmd;
-    match cmd.to_ascii_uppercase().as_str() {
-        "PING" => {
-            RespValue::SimpleString("PONG".to_string()).encode()
-        },
-        "ECHO" => {
-            let args = command.args;
-            if let Some(echo_arg) = args.get(0) {
-                RespValue::SimpleString(echo_arg.to_string()).encode()
-            } else {
-                RespValue::SimpleString("".to_string()).encode()
-            }
-        }
-        "SET" => {
-            // SET [key] [value]
-            let args = command.args;
-            if let (Some(key), Some(value)) = (args.get(0), args.get(1)) {
-
-                if let (Some(_), Some(expiry)) = (args.get(2), args.get(3)) {
-                    client_store.lock().unwrap().set_px(key.clone(), value.clone(), expiry.parse::<u64>().unwrap());
-                } else {
-                    client_store.lock().unwrap().set(key.clone(), value.clone());
-                }
+    #[test]
+    fn test_bulk_string_encodes_with_length_prefix() {
+        let encoded = RespValue::BulkString("PONG".to_string()).encode();
+        assert_eq!(encoded, "$4\r\nPONG\r\n");
+    }
 
-                RespValue::SimpleString("OK".to_string()).encode()
-            } else {
-                RespValue::Error("SET requires exactly two arguments".to_string()).encode()
-            }
-        }
-        "GET" => {
-            // GET [key]
-            let args = command.args;
-            if let Some(key) = args.get(0) {
-                if let Some(value) = client_store.lock().unwrap().get(key.clone()) {
-                    RespValue::SimpleString(value).encode()
-                } else {
-                    RespValue::BulkString(NULL_DENOTE.to_string()).encode()
-                }
-            } else {
-                RespValue::Error("GET requires exactly one argument".to_string()).encode()
-            }
-        }
-        _ => RespValue::Error(format!("{ERROR_UNKNOWN_COMMAND} '{cmd}'")).encode()
+    #[test]
+    fn test_null_encodes_as_null_bulk_string() {
+        assert_eq!(RespValue::Null.encode(), "$-1\r\n");
+    }
+
+    #[test]
+    fn test_array_encodes_nested_elements() {
+        let encoded = RespValue::Array(vec![
+            RespValue::BulkString("message".to_string()),
+            RespValue::Integer(2),
+        ]).encode();
+        assert_eq!(encoded, "*2\r\n$7\r\nmessage\r\n:2\r\n");
     }
-}
 
+    #[test]
+    fn test_parse_command_complete_frame() {
+        let mut buf = BytesMut::from("*2\r\n$4\r\nPING\r\n$4\r\nPONG\r\n");
+        let cmd = parse_command(&mut buf).unwrap().unwrap();
+        assert_eq!(cmd.cmd, "PING");
+        assert_eq!(cmd.args, vec!["PONG".to_string()]);
+        assert!(buf.is_empty());
+    }
 
-// --- TESTING ---
+    #[test]
+    fn test_parse_command_incomplete_frame_leaves_buffer_untouched() {
+        // Value length claims 4 bytes but only 2 have arrived so far.
+        let mut buf = BytesMut::from("*1\r\n$4\r\nPI");
+        let before = buf.clone();
+        let result = parse_command(&mut buf);
+        assert!(matches!(result, Err(ParseError::Incomplete)));
+        assert_eq!(buf, before);
+    }
 
-#[cfg(test)]
-mod cmd_tests {
-    use super::deserialize_command_into_array;
+    #[test]
+    fn test_parse_command_leaves_pipelined_command_buffered() {
+        let mut buf = BytesMut::from("*1\r\n$4\r\nPING\r\n*1\r\n$4\r\nPING\r\n");
+        let first = parse_command(&mut buf).unwrap().unwrap();
+        assert_eq!(first.cmd, "PING");
+        assert_eq!(buf, BytesMut::from("*1\r\n$4\r\nPING\r\n"));
+
+        let second = parse_command(&mut buf).unwrap().unwrap();
+        assert_eq!(second.cmd, "PING");
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_parse_command_rejects_empty_array() {
+        let mut buf = BytesMut::from("*0\r\n");
+        let result = parse_command(&mut buf);
+        assert!(matches!(result, Err(ParseError::Protocol(_))));
+    }
+
+    #[test]
+    fn test_parse_command_rejects_null_array() {
+        let mut buf = BytesMut::from("*-1\r\n");
+        let result = parse_command(&mut buf);
+        assert!(matches!(result, Err(ParseError::Protocol(_))));
+    }
+
+    #[test]
+    fn test_parse_command_rejects_null_command_name() {
+        let mut buf = BytesMut::from("*1\r\n$-1\r\n");
+        let result = parse_command(&mut buf);
+        assert!(matches!(result, Err(ParseError::Protocol(_))));
+    }
 
     #[test]
-    fn test_deserialize_array_command_successfully() {
-        let test_cmd = String::from("*2\r\n$4\r\nPING\r\n$4\r\nPONG\r\n");
-        let expect_array: Vec<Option<String>> =
-            vec![Some(String::from("PING")), Some(String::from("PONG"))];
-        let cmd_array = deserialize_command_into_array(&test_cmd);
-        assert_eq!(expect_array, cmd_array.unwrap());
+    fn test_parse_command_rejects_oversized_multibulk_length_without_allocating() {
+        // A count this large would abort the process if trusted for
+        // Vec::with_capacity; it must be rejected before any allocation.
+        let mut buf = BytesMut::from("*999999999999\r\n");
+        let result = parse_command(&mut buf);
+        assert!(matches!(result, Err(ParseError::Protocol(_))));
     }
 
     #[test]
-    fn test_deserialize_array_2_commands_successfully() {
-        let test_cmd1 = String::from("*2\r\n$4\r\nPING\r\n$4\r\nPONG\r\n");
-        let expect_array1: Vec<Option<String>> =
-            vec![Some(String::from("PING")), Some(String::from("PONG"))];
-        let cmd_array1 = deserialize_command_into_array(&test_cmd1);
-        assert_eq!(expect_array1, cmd_array1.unwrap());
-
-        let expect_array2: Vec<Option<String>> = vec![Some(String::from("PING"))];
-        let test_cmd2 = String::from("*1\r\n$4\r\nPING\r\n");
-        let cmd_array2 = deserialize_command_into_array(&test_cmd2);
-        assert_eq!(expect_array2, cmd_array2.unwrap());
+    fn test_parse_command_rejects_invalid_utf8_bulk_string() {
+        let mut buf = BytesMut::from(&b"*1\r\n$4\r\n\xff\xfe\x00A\r\n"[..]);
+        let result = parse_command(&mut buf);
+        assert!(matches!(result, Err(ParseError::Protocol(_))));
     }
 }
@@ -0,0 +1,43 @@
+use std::env;
+
+const DEFAULT_TLS_BIND_ADDR: &str = "127.0.0.1:6380";
+const DEFAULT_WS_BIND_ADDR: &str = "127.0.0.1:6381";
+
+/// Server-wide configuration loaded from the environment at startup.
+pub struct Config {
+    /// When set, clients must authenticate via `AUTH` or `HELLO ... AUTH`
+    /// before any other command is accepted.
+    pub password: Option<String>,
+    /// PEM certificate chain path for the optional TLS listener.
+    pub tls_cert_path: Option<String>,
+    /// PEM private key path for the optional TLS listener.
+    pub tls_key_path: Option<String>,
+    /// Address the TLS listener binds when enabled.
+    pub tls_bind_addr: String,
+    /// Enables the WebSocket listener for browser/edge clients.
+    pub ws_enabled: bool,
+    /// Address the WebSocket listener binds when enabled.
+    pub ws_bind_addr: String,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            password: env::var("REDIS_PASSWORD").ok(),
+            tls_cert_path: env::var("REDIS_TLS_CERT_PATH").ok(),
+            tls_key_path: env::var("REDIS_TLS_KEY_PATH").ok(),
+            tls_bind_addr: env::var("REDIS_TLS_BIND_ADDR")
+                .unwrap_or_else(|_| DEFAULT_TLS_BIND_ADDR.to_string()),
+            ws_enabled: env::var("REDIS_WS_ENABLED")
+                .map(|val| val == "1" || val.eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            ws_bind_addr: env::var("REDIS_WS_BIND_ADDR")
+                .unwrap_or_else(|_| DEFAULT_WS_BIND_ADDR.to_string()),
+        }
+    }
+
+    /// TLS is enabled once both a certificate and a private key are configured.
+    pub fn tls_enabled(&self) -> bool {
+        self.tls_cert_path.is_some() && self.tls_key_path.is_some()
+    }
+}
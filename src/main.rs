@@ -1,27 +1,79 @@
 mod utils;
 mod store;
 mod cmd;
+mod config;
+mod tls;
+mod ws;
 
 use std::sync::{Arc, Mutex};
 use anyhow::Result;
 use tokio::net::TcpListener;
 use utils::handle_connection;
-use crate::store::Store;
+use crate::config::Config;
+use crate::store::{Broker, Store};
+use crate::tls::build_tls_acceptor;
+use crate::ws::run_ws_listener;
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let listener = TcpListener::bind("127.0.0.1:6379").await?;
     let main_store = Arc::new(Mutex::new(Store::new()));
+    let broker = Arc::new(Mutex::new(Broker::new()));
+    let config = Arc::new(Config::from_env());
+
+    if config.tls_enabled() {
+        let tls_acceptor = build_tls_acceptor(&config)?;
+        let tls_listener = TcpListener::bind(&config.tls_bind_addr).await?;
+        let tls_store = main_store.clone();
+        let tls_broker = broker.clone();
+        let tls_config = config.clone();
+        println!("accepting TLS connections on {}", config.tls_bind_addr);
+
+        tokio::spawn(async move {
+            loop {
+                let incoming = tls_listener.accept().await;
+                let client_store = tls_store.clone();
+                let client_broker = tls_broker.clone();
+                let client_config = tls_config.clone();
+                let acceptor = tls_acceptor.clone();
+
+                match incoming {
+                    Ok((stream, _)) => {
+                        tokio::spawn(async move {
+                            match acceptor.accept(stream).await {
+                                Ok(tls_stream) => {
+                                    println!("accepted new TLS connection");
+                                    handle_connection(tls_stream, client_store, client_broker, client_config).await.unwrap();
+                                }
+                                Err(e) => eprintln!("TLS handshake error: {e}"),
+                            }
+                        });
+                    }
+                    Err(e) => {
+                        eprintln!("error: {e}");
+                    }
+                }
+            }
+        });
+    }
+
+    if config.ws_enabled {
+        let ws_listener = TcpListener::bind(&config.ws_bind_addr).await?;
+        println!("accepting WebSocket connections on {}", config.ws_bind_addr);
+        tokio::spawn(run_ws_listener(ws_listener, main_store.clone(), broker.clone(), config.clone()));
+    }
 
     loop {
         let incoming = listener.accept().await;
         let client_store = main_store.clone();
+        let client_broker = broker.clone();
+        let client_config = config.clone();
 
         match incoming {
             Ok((stream, _)) => {
                 println!("accepted new connection");
                 tokio::spawn(async move {
-                    handle_connection(stream, client_store).await.unwrap();
+                    handle_connection(stream, client_store, client_broker, client_config).await.unwrap();
                 });
             }
             Err(e) => {
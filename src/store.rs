@@ -1,6 +1,13 @@
 use std::collections::HashMap;
-use std::time::Duration;
+use tokio::sync::mpsc;
 use tokio::time::Instant;
+use crate::cmd::RespValue;
+
+/// Identifies a single connected client across the broker's subscriber maps.
+pub type ClientId = u64;
+
+/// Mailbox capacity for a subscriber's push channel.
+const SUBSCRIBER_CHANNEL_CAPACITY: usize = 64;
 
 pub struct Entry {
     value: String,
@@ -26,20 +33,11 @@ impl Store {
         self.data.insert(key, entry);
     }
 
-    pub fn set_px(&mut self, key: String, value: String, px: u64) {
-        let entry = Entry {
-            value,
-            expiry: Some(Instant::now() + Duration::from_millis(px)),
-        };
-        // TODO: Implement lazy deletion expired key
-        self.data.insert(key, entry);
-    }
-
     pub fn get(&mut self, key: String) -> Option<String> {
         match self.data.get(key.as_str()) {
             Some(entry) => {
                 if let Some(expiry) = &entry.expiry {
-                    if Instant::now() > expiry.clone() {
+                    if Instant::now() > *expiry {
                         self.data.remove(key.as_str());
                         return None;
                     }
@@ -50,4 +48,146 @@ impl Store {
             None => None
         }
     }
+}
+
+/// Pub/Sub registry: tracks which clients are subscribed to which channels
+/// and holds each client's delivery channel so `PUBLISH` can fan a message
+/// out without the publisher blocking on the subscriber's socket write.
+pub struct Broker {
+    next_client_id: ClientId,
+    channels: HashMap<String, Vec<ClientId>>,
+    senders: HashMap<ClientId, mpsc::Sender<String>>,
+}
+
+impl Broker {
+    pub fn new() -> Self {
+        Broker {
+            next_client_id: 0,
+            channels: HashMap::new(),
+            senders: HashMap::new(),
+        }
+    }
+
+    /// Register a new connection and hand back its id and the receiving end
+    /// of its push mailbox; the connection loop selects on this alongside
+    /// its socket read.
+    pub fn register(&mut self) -> (ClientId, mpsc::Receiver<String>) {
+        let client_id = self.next_client_id;
+        self.next_client_id += 1;
+
+        let (tx, rx) = mpsc::channel(SUBSCRIBER_CHANNEL_CAPACITY);
+        self.senders.insert(client_id, tx);
+        (client_id, rx)
+    }
+
+    /// Subscribe a client to `channel`, returning the number of channels it
+    /// is now subscribed to in total.
+    pub fn subscribe(&mut self, channel: String, client_id: ClientId) -> usize {
+        let subscribers = self.channels.entry(channel).or_default();
+        if !subscribers.contains(&client_id) {
+            subscribers.push(client_id);
+        }
+        self.subscription_count(client_id)
+    }
+
+    /// Unsubscribe a client from `channel`, returning the number of channels
+    /// it remains subscribed to.
+    pub fn unsubscribe(&mut self, channel: &str, client_id: ClientId) -> usize {
+        if let Some(subscribers) = self.channels.get_mut(channel) {
+            subscribers.retain(|&id| id != client_id);
+        }
+        self.subscription_count(client_id)
+    }
+
+    fn subscription_count(&self, client_id: ClientId) -> usize {
+        self.channels.values().filter(|subs| subs.contains(&client_id)).count()
+    }
+
+    /// Fan `message` out to every subscriber of `channel`, returning how many
+    /// of them the message was actually delivered to.
+    pub fn publish(&self, channel: &str, message: &str) -> usize {
+        let Some(subscribers) = self.channels.get(channel) else {
+            return 0;
+        };
+
+        let push = RespValue::Array(vec![
+            RespValue::BulkString("message".to_string()),
+            RespValue::BulkString(channel.to_string()),
+            RespValue::BulkString(message.to_string()),
+        ]).encode();
+
+        subscribers.iter()
+            .filter_map(|id| self.senders.get(id))
+            .filter(|sender| sender.try_send(push.clone()).is_ok())
+            .count()
+    }
+
+    /// Drop a closed connection's mailbox and remove it from every channel
+    /// it was subscribed to, mirroring the cleanup a `Drop` impl would do
+    /// for the connection's client guard.
+    pub fn deregister(&mut self, client_id: ClientId) {
+        self.senders.remove(&client_id);
+        for subscribers in self.channels.values_mut() {
+            subscribers.retain(|&id| id != client_id);
+        }
+    }
+}
+
+
+// --- TESTING ---
+
+#[cfg(test)]
+mod store_tests {
+    use super::Broker;
+
+    #[test]
+    fn test_subscribe_returns_subscription_count() {
+        let mut broker = Broker::new();
+        let (client_id, _rx) = broker.register();
+
+        assert_eq!(broker.subscribe("a".to_string(), client_id), 1);
+        assert_eq!(broker.subscribe("b".to_string(), client_id), 2);
+        // Re-subscribing to the same channel doesn't double-count it.
+        assert_eq!(broker.subscribe("a".to_string(), client_id), 2);
+    }
+
+    #[test]
+    fn test_unsubscribe_returns_remaining_subscription_count() {
+        let mut broker = Broker::new();
+        let (client_id, _rx) = broker.register();
+
+        broker.subscribe("a".to_string(), client_id);
+        broker.subscribe("b".to_string(), client_id);
+
+        assert_eq!(broker.unsubscribe("a", client_id), 1);
+        assert_eq!(broker.unsubscribe("b", client_id), 0);
+        // Unsubscribing from a channel never joined is a no-op.
+        assert_eq!(broker.unsubscribe("c", client_id), 0);
+    }
+
+    #[test]
+    fn test_publish_delivers_only_to_subscribers_of_that_channel() {
+        let mut broker = Broker::new();
+        let (subscriber, mut rx) = broker.register();
+        let (_other, _other_rx) = broker.register();
+
+        broker.subscribe("news".to_string(), subscriber);
+
+        assert_eq!(broker.publish("news", "hello"), 1);
+        assert_eq!(broker.publish("sports", "hello"), 0);
+
+        let pushed = rx.try_recv().unwrap();
+        assert_eq!(pushed, "*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n");
+    }
+
+    #[test]
+    fn test_deregister_removes_subscriptions_and_mailbox() {
+        let mut broker = Broker::new();
+        let (client_id, _rx) = broker.register();
+        broker.subscribe("news".to_string(), client_id);
+
+        broker.deregister(client_id);
+
+        assert_eq!(broker.publish("news", "hello"), 0);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,30 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::Config;
+
+/// Build a `TlsAcceptor` from the certificate chain and private key paths in
+/// `config`. Only called once `config.tls_enabled()` is true.
+pub fn build_tls_acceptor(config: &Config) -> Result<TlsAcceptor> {
+    let cert_path = config.tls_cert_path.as_ref().ok_or_else(|| anyhow!("TLS cert path not configured"))?;
+    let key_path = config.tls_key_path.as_ref().ok_or_else(|| anyhow!("TLS key path not configured"))?;
+
+    let mut cert_reader = BufReader::new(File::open(cert_path)?);
+    let certs: Vec<CertificateDer> = rustls_pemfile::certs(&mut cert_reader).collect::<Result<_, _>>()?;
+
+    let mut key_reader = BufReader::new(File::open(key_path)?);
+    let key: PrivateKeyDer = rustls_pemfile::private_key(&mut key_reader)?
+        .ok_or_else(|| anyhow!("no private key found in {key_path}"))?;
+
+    let tls_config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?;
+
+    Ok(TlsAcceptor::from(Arc::new(tls_config)))
+}
@@ -1,28 +1,92 @@
 use std::sync::{Arc, Mutex};
 use anyhow::Result;
 use bytes::BytesMut;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use crate::cmd::{RespValue, deserialize_array_command, parse_cmd, Command};
-use crate::store::Store;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use crate::cmd::{RespValue, parse_command, Command, ParseError};
+use crate::config::Config;
+use crate::store::{Broker, ClientId, Store};
 
 const BUFFER_SIZE_LIMIT: usize = 512; // in MB
 const ERROR_UNKNOWN_COMMAND: &str = "ERR unknown command";
-const ERROR_EMPTY_COMMAND: &str = "ERR empty command";
+const SERVER_NAME: &str = "redis";
+const SERVER_VERSION: &str = "7.0.0";
+const DEFAULT_PROTO_VERSION: u8 = 2;
 
-pub fn buf_to_string(buf: &mut BytesMut, size: usize) -> String {
-    let utf8_str = String::from_utf8_lossy(&buf[..size]);
-    return utf8_str.into_owned();
+/// Per-connection negotiation state set by `HELLO`/`AUTH`.
+pub struct ConnectionState {
+    proto_version: u8,
+    authenticated: bool,
 }
 
-pub fn handle_command_response(cmd: Command, client_store: &Arc<Mutex<Store>>) -> String {
+impl ConnectionState {
+    fn new(config: &Config) -> Self {
+        ConnectionState {
+            proto_version: DEFAULT_PROTO_VERSION,
+            authenticated: config.password.is_none(),
+        }
+    }
+}
+
+/// RESP-encode the `HELLO` reply: a flat array of server metadata pairs,
+/// mirroring Redis's RESP2 fallback shape for `HELLO`.
+fn encode_hello_reply(proto_version: u8) -> String {
+    RespValue::Array(vec![
+        RespValue::BulkString("server".to_string()),
+        RespValue::BulkString(SERVER_NAME.to_string()),
+        RespValue::BulkString("version".to_string()),
+        RespValue::BulkString(SERVER_VERSION.to_string()),
+        RespValue::BulkString("proto".to_string()),
+        RespValue::Integer(proto_version as i64),
+        RespValue::BulkString("mode".to_string()),
+        RespValue::BulkString("standalone".to_string()),
+        RespValue::BulkString("role".to_string()),
+        RespValue::BulkString("master".to_string()),
+        RespValue::BulkString("modules".to_string()),
+        RespValue::Array(vec![]),
+    ]).encode()
+}
+
+/// Check `pass` against the configured password. Callers accept a username
+/// but never pass it here — this store has no user database.
+fn check_auth(config: &Config, pass: &str) -> Result<(), String> {
+    match &config.password {
+        None => Err("ERR Client sent AUTH, but no password is set. Did you mean AUTH <user> <password>?".to_string()),
+        Some(expected) if pass == expected => Ok(()),
+        Some(_) => Err("WRONGPASS invalid username-password pair or user is disabled.".to_string()),
+    }
+}
+
+/// RESP-encode a subscribe/unsubscribe acknowledgement as a 3-element array:
+/// `[kind, channel, count]`.
+fn encode_sub_ack(kind: &str, channel: &str, count: usize) -> String {
+    RespValue::Array(vec![
+        RespValue::BulkString(kind.to_string()),
+        RespValue::BulkString(channel.to_string()),
+        RespValue::Integer(count as i64),
+    ]).encode()
+}
+
+pub fn handle_command_response(
+    cmd: Command,
+    client_store: &Arc<Mutex<Store>>,
+    client_id: ClientId,
+    broker: &Arc<Mutex<Broker>>,
+    conn_state: &mut ConnectionState,
+    config: &Config,
+) -> String {
     let main_cmd = cmd.cmd;
-    match main_cmd.to_ascii_uppercase().as_str() {
+    let upper_cmd = main_cmd.to_ascii_uppercase();
+
+    if !conn_state.authenticated && upper_cmd != "HELLO" && upper_cmd != "AUTH" {
+        return RespValue::Error("NOAUTH Authentication required.".to_string()).encode();
+    }
+
+    match upper_cmd.as_str() {
         "PING" => {
             RespValue::SimpleString("PONG".to_string()).encode()
         },
         "ECHO" => {
-            if let Some(echo_arg) = cmd.args.get(0) {
+            if let Some(echo_arg) = cmd.args.first() {
                 RespValue::SimpleString(echo_arg.to_string()).encode()
             } else {
                 RespValue::SimpleString("".to_string()).encode()
@@ -30,7 +94,7 @@ pub fn handle_command_response(cmd: Command, client_store: &Arc<Mutex<Store>>) -
         }
         "SET" => {
             // SET [key] [value]
-            if let (Some(key), Some(value)) = (cmd.args.get(0), cmd.args.get(1)) {
+            if let (Some(key), Some(value)) = (cmd.args.first(), cmd.args.get(1)) {
                 client_store.lock().unwrap().set(key.clone(), value.clone());
                 RespValue::SimpleString("OK".to_string()).encode()
             } else {
@@ -39,50 +103,285 @@ pub fn handle_command_response(cmd: Command, client_store: &Arc<Mutex<Store>>) -
         }
         "GET" => {
             // GET [key]
-            if let Some(key) = cmd.args.get(0) {
+            if let Some(key) = cmd.args.first() {
                 if let Some(value) = client_store.lock().unwrap().get(key.clone()) {
-                    RespValue::SimpleString(value).encode()
+                    RespValue::BulkString(value).encode()
                 } else {
-                    RespValue::SimpleString("-1".to_string()).encode()
+                    RespValue::Null.encode()
                 }
             } else {
                 RespValue::Error("GET requires exactly one argument".to_string()).encode()
             }
         }
+        "SUBSCRIBE" => {
+            if let Some(channel) = cmd.args.first() {
+                let count = broker.lock().unwrap().subscribe(channel.clone(), client_id);
+                encode_sub_ack("subscribe", channel, count)
+            } else {
+                RespValue::Error("SUBSCRIBE requires exactly one argument".to_string()).encode()
+            }
+        }
+        "UNSUBSCRIBE" => {
+            if let Some(channel) = cmd.args.first() {
+                let count = broker.lock().unwrap().unsubscribe(channel, client_id);
+                encode_sub_ack("unsubscribe", channel, count)
+            } else {
+                RespValue::Error("UNSUBSCRIBE requires exactly one argument".to_string()).encode()
+            }
+        }
+        "PUBLISH" => {
+            if let (Some(channel), Some(message)) = (cmd.args.first(), cmd.args.get(1)) {
+                let delivered = broker.lock().unwrap().publish(channel, message);
+                RespValue::Integer(delivered as i64).encode()
+            } else {
+                RespValue::Error("PUBLISH requires exactly two arguments".to_string()).encode()
+            }
+        }
+        "AUTH" => {
+            if let Some(pass) = cmd.args.last() {
+                match check_auth(config, pass) {
+                    Ok(()) => {
+                        conn_state.authenticated = true;
+                        RespValue::SimpleString("OK".to_string()).encode()
+                    }
+                    Err(err) => RespValue::Error(err).encode(),
+                }
+            } else {
+                RespValue::Error("ERR wrong number of arguments for 'auth' command".to_string()).encode()
+            }
+        }
+        "HELLO" => {
+            let mut args = cmd.args.iter();
+
+            let proto_version = match args.next() {
+                Some(protover) => match protover.as_str() {
+                    "2" => 2,
+                    "3" => 3,
+                    _ => return RespValue::Error(format!("NOPROTO unsupported protocol version '{protover}'")).encode(),
+                },
+                None => conn_state.proto_version,
+            };
+
+            let remaining: Vec<&String> = args.collect();
+            if let Some(pos) = remaining.iter().position(|arg| arg.eq_ignore_ascii_case("AUTH")) {
+                match (remaining.get(pos + 1), remaining.get(pos + 2)) {
+                    (Some(_user), Some(pass)) => {
+                        if let Err(err) = check_auth(config, pass) {
+                            return RespValue::Error(err).encode();
+                        }
+                        conn_state.authenticated = true;
+                    }
+                    _ => return RespValue::Error("ERR syntax error in HELLO".to_string()).encode(),
+                }
+            }
+            if !conn_state.authenticated {
+                return RespValue::Error("NOAUTH Authentication required.".to_string()).encode();
+            }
+
+            conn_state.proto_version = proto_version;
+            encode_hello_reply(proto_version)
+        }
         _ => RespValue::Error(format!("{ERROR_UNKNOWN_COMMAND} '{main_cmd}'")).encode()
     }
 }
 
+/// Deregisters a client's mailbox and channel subscriptions when its
+/// connection loop exits, mirroring the `ClientInner` drop cleanup pattern.
+struct ClientGuard {
+    broker: Arc<Mutex<Broker>>,
+    client_id: ClientId,
+}
+
+impl Drop for ClientGuard {
+    fn drop(&mut self) {
+        self.broker.lock().unwrap().deregister(self.client_id);
+    }
+}
+
 
 /// Handle TCP connection from client
 ///
+/// Owns a `BytesMut` across read iterations and drains every complete,
+/// pipelined command buffered in it before going back to the socket, so a
+/// value split across TCP segments is parsed correctly and commands sent
+/// back-to-back by the client don't need a read each. Registers with
+/// `broker` up front so the loop can `select!` between the socket read and
+/// messages pushed to this client by another connection's `PUBLISH`.
+///
+/// Generic over `AsyncRead + AsyncWrite` so the same loop serves the
+/// plaintext `TcpStream` listener, TLS-wrapped connections, and the
+/// WebSocket transport's byte-pipe adapter alike.
+///
 /// # Arguments
 ///
-/// * `stream` - TCP Stream
+/// * `stream` - Plain or TLS-wrapped duplex stream
 /// * `client_store` Client Store
+/// * `broker` Pub/Sub registry shared across all connections
+/// * `config` Server-wide configuration (e.g. the configured password)
 ///
 /// # Returns
 ///
 /// Connection Result (Failed or not)
-pub async fn handle_connection(mut stream: TcpStream, client_store: Arc<Mutex<Store>>) -> Result<()> {
+pub async fn handle_connection<S>(
+    mut stream: S,
+    client_store: Arc<Mutex<Store>>,
+    broker: Arc<Mutex<Broker>>,
+    config: Arc<Config>,
+) -> Result<()>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
     let mut buf = BytesMut::with_capacity(BUFFER_SIZE_LIMIT);
+    let (client_id, mut push_rx) = broker.lock().unwrap().register();
+    let _client_guard = ClientGuard { broker: broker.clone(), client_id };
+    let mut conn_state = ConnectionState::new(&config);
+
     loop {
-        let bytes_read = stream.read_buf(&mut buf).await?;
-        if bytes_read == 0 {
-            println!("Client closed the connection");
-            break;
-        }
-        let cmd_str = buf_to_string(&mut buf, bytes_read);
-        let resp = match deserialize_array_command(&cmd_str) {
-            Some(cmd_array) => {
-                let cmd = parse_cmd(cmd_array);
-                handle_command_response(cmd, &client_store)
+        loop {
+            match parse_command(&mut buf) {
+                Ok(Some(cmd)) => {
+                    let resp = handle_command_response(cmd, &client_store, client_id, &broker, &mut conn_state, &config);
+                    stream.write_all(resp.as_bytes()).await?;
+                }
+                Ok(None) => break,
+                Err(ParseError::Incomplete) => break,
+                Err(ParseError::Protocol(msg)) => {
+                    println!("closing connection after protocol error: {msg}");
+                    stream.write_all(RespValue::Error(msg).encode().as_bytes()).await?;
+                    return Ok(());
+                }
             }
-            None => RespValue::Error(format!("{ERROR_EMPTY_COMMAND}")).encode()
-        };
+        }
 
-        stream.write(resp.as_bytes()).await?;
-        buf.clear();
+        tokio::select! {
+            bytes_read = stream.read_buf(&mut buf) => {
+                if bytes_read? == 0 {
+                    println!("Client closed the connection");
+                    break;
+                }
+            }
+            pushed = push_rx.recv() => {
+                if let Some(message) = pushed {
+                    stream.write_all(message.as_bytes()).await?;
+                }
+            }
+        }
     }
     Ok(())
 }
+
+
+// --- TESTING ---
+
+#[cfg(test)]
+mod utils_tests {
+    use std::sync::{Arc, Mutex};
+    use crate::config::Config;
+    use crate::store::{Broker, Store};
+    use super::{handle_command_response, check_auth, ConnectionState};
+
+    fn config_with_password(password: Option<&str>) -> Config {
+        Config {
+            password: password.map(str::to_string),
+            tls_cert_path: None,
+            tls_key_path: None,
+            tls_bind_addr: String::new(),
+            ws_enabled: false,
+            ws_bind_addr: String::new(),
+        }
+    }
+
+    fn dispatch(cmd: super::Command, config: &Config, conn_state: &mut ConnectionState) -> String {
+        let store = Arc::new(Mutex::new(Store::new()));
+        let broker = Arc::new(Mutex::new(Broker::new()));
+        handle_command_response(cmd, &store, 0, &broker, conn_state, config)
+    }
+
+    fn cmd(name: &str, args: &[&str]) -> super::Command {
+        super::Command {
+            cmd: name.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_check_auth_without_configured_password_errors() {
+        let config = config_with_password(None);
+        assert_eq!(
+            check_auth(&config, "anything"),
+            Err("ERR Client sent AUTH, but no password is set. Did you mean AUTH <user> <password>?".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_auth_wrong_password_errors() {
+        let config = config_with_password(Some("secret"));
+        assert_eq!(
+            check_auth(&config, "wrong"),
+            Err("WRONGPASS invalid username-password pair or user is disabled.".to_string())
+        );
+    }
+
+    #[test]
+    fn test_check_auth_correct_password_ok() {
+        let config = config_with_password(Some("secret"));
+        assert_eq!(check_auth(&config, "secret"), Ok(()));
+    }
+
+    #[test]
+    fn test_unauthenticated_client_blocked_until_auth_succeeds() {
+        let config = config_with_password(Some("secret"));
+        let mut conn_state = ConnectionState::new(&config);
+
+        let resp = dispatch(cmd("PING", &[]), &config, &mut conn_state);
+        assert_eq!(resp, "-NOAUTH Authentication required.\r\n");
+
+        let resp = dispatch(cmd("AUTH", &["secret"]), &config, &mut conn_state);
+        assert_eq!(resp, "+OK\r\n");
+        assert!(conn_state.authenticated);
+
+        let resp = dispatch(cmd("PING", &[]), &config, &mut conn_state);
+        assert_eq!(resp, "+PONG\r\n");
+    }
+
+    #[test]
+    fn test_hello_without_protover_keeps_current_version() {
+        let config = config_with_password(None);
+        let mut conn_state = ConnectionState::new(&config);
+        conn_state.proto_version = 3;
+
+        let resp = dispatch(cmd("HELLO", &[]), &config, &mut conn_state);
+        assert!(resp.contains("proto"));
+        assert_eq!(conn_state.proto_version, 3);
+    }
+
+    #[test]
+    fn test_hello_rejects_unsupported_protover() {
+        let config = config_with_password(None);
+        let mut conn_state = ConnectionState::new(&config);
+
+        let resp = dispatch(cmd("HELLO", &["4"]), &config, &mut conn_state);
+        assert_eq!(resp, "-NOPROTO unsupported protocol version '4'\r\n");
+    }
+
+    #[test]
+    fn test_hello_with_auth_clause_authenticates() {
+        let config = config_with_password(Some("secret"));
+        let mut conn_state = ConnectionState::new(&config);
+        assert!(!conn_state.authenticated);
+
+        let resp = dispatch(cmd("HELLO", &["2", "AUTH", "default", "secret"]), &config, &mut conn_state);
+        assert!(resp.contains("proto"));
+        assert!(conn_state.authenticated);
+    }
+
+    #[test]
+    fn test_hello_with_wrong_auth_clause_stays_unauthenticated() {
+        let config = config_with_password(Some("secret"));
+        let mut conn_state = ConnectionState::new(&config);
+
+        let resp = dispatch(cmd("HELLO", &["2", "AUTH", "default", "wrong"]), &config, &mut conn_state);
+        assert_eq!(resp, "-WRONGPASS invalid username-password pair or user is disabled.\r\n");
+        assert!(!conn_state.authenticated);
+    }
+}
@@ -0,0 +1,114 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use async_tungstenite::tokio::accept_async;
+use async_tungstenite::tungstenite::Message;
+use futures_util::StreamExt;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::config::Config;
+use crate::store::{Broker, Store};
+use crate::utils::handle_connection;
+
+const BUFFER_SIZE_LIMIT: usize = 512; // in bytes
+
+/// Accept WebSocket upgrade requests and bridge each connection's binary
+/// frames into the same command pipeline the native TCP listener uses, so
+/// browser/edge clients that can't open a raw TCP socket can still speak
+/// RESP to the store.
+pub async fn run_ws_listener(
+    listener: TcpListener,
+    store: Arc<Mutex<Store>>,
+    broker: Arc<Mutex<Broker>>,
+    config: Arc<Config>,
+) {
+    loop {
+        let incoming = listener.accept().await;
+        let client_store = store.clone();
+        let client_broker = broker.clone();
+        let client_config = config.clone();
+
+        match incoming {
+            Ok((stream, _)) => {
+                tokio::spawn(async move {
+                    if let Err(e) = handle_ws_connection(stream, client_store, client_broker, client_config).await {
+                        eprintln!("WebSocket connection error: {e}");
+                    }
+                });
+            }
+            Err(e) => {
+                eprintln!("error: {e}");
+            }
+        }
+    }
+}
+
+/// Handle a single WebSocket connection by bridging it onto an in-memory
+/// duplex byte pipe and handing the other end to the shared
+/// `handle_connection` loop, so the command pipeline itself stays transport
+/// agnostic instead of being duplicated per transport.
+///
+/// The pump below is the only WS-specific part: it forwards binary frames
+/// in as bytes and bytes out as binary frames, and answers `Ping` frames
+/// with `Pong` — keepalive housekeeping that has nothing to do with RESP
+/// and so has no business living in `handle_connection`.
+async fn handle_ws_connection(
+    stream: TcpStream,
+    client_store: Arc<Mutex<Store>>,
+    broker: Arc<Mutex<Broker>>,
+    config: Arc<Config>,
+) -> Result<()> {
+    let mut ws_stream = accept_async(stream).await?;
+    println!("accepted new WebSocket connection");
+
+    let (client_side, server_side) = tokio::io::duplex(BUFFER_SIZE_LIMIT);
+    let (mut pipe_read, mut pipe_write) = tokio::io::split(client_side);
+
+    let pump = tokio::spawn(async move {
+        let mut out_buf = [0u8; BUFFER_SIZE_LIMIT];
+        loop {
+            tokio::select! {
+                msg = ws_stream.next() => {
+                    match msg {
+                        Some(Ok(Message::Binary(data))) => {
+                            if pipe_write.write_all(&data).await.is_err() {
+                                break;
+                            }
+                        }
+                        Some(Ok(Message::Ping(payload))) => {
+                            if ws_stream.send(Message::Pong(payload)).await.is_err() {
+                                break;
+                            }
+                        }
+                        Some(Ok(Message::Close(_))) | None => {
+                            println!("WebSocket client closed the connection");
+                            break;
+                        }
+                        Some(Ok(_non_binary)) => {
+                            // Pongs/text frames carry no RESP payload; ignore them.
+                        }
+                        Some(Err(e)) => {
+                            eprintln!("WebSocket read error: {e}");
+                            break;
+                        }
+                    }
+                }
+                read = pipe_read.read(&mut out_buf) => {
+                    match read {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => {
+                            if ws_stream.send(Message::Binary(out_buf[..n].to_vec())).await.is_err() {
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    handle_connection(server_side, client_store, broker, config).await?;
+    pump.abort();
+    Ok(())
+}